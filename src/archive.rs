@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use rayon::prelude::*;
+use tracing::{debug, error};
+
+use crate::db::Database;
+use crate::document::{self, Document};
+use crate::error::KnawledgeError;
+use crate::job::{self, Job, JobReport, JobState};
+
+/// A `.md` member read out of an archive, still holding its raw bytes so
+/// parsing can run on the rayon pool instead of on the (blocking) extraction
+/// path.
+struct ArchiveEntry {
+    /// Path relative to the archive root, e.g. `notes/a.md`.
+    relative: PathBuf,
+    bytes: Vec<u8>,
+}
+
+/// Whether `path` looks like a source this module knows how to ingest,
+/// rather than a plain directory.
+pub fn is_archive(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz") || name.ends_with(".zip")
+}
+
+/// Runs [`process_archive`] as an observable, cancellable [`Job`], mirroring
+/// `document::process_directory_job`.
+pub async fn process_archive_job(
+    db: &Database,
+    path: &Path,
+    job: &Job,
+) -> Result<JobReport, KnawledgeError> {
+    let mut report = JobReport::queued(job.id);
+    report.state = JobState::Running;
+    db.insert_job_report(&report).await?;
+
+    job::register(job);
+    let result = process_archive(db, path, job).await;
+    job::unregister(&job.id);
+
+    let (discovered, processed) = job.progress();
+    report.files_discovered = discovered;
+    report.files_processed = processed;
+    report.finished_at = Some(Utc::now());
+    report.state = match &result {
+        _ if job.is_cancelled() => JobState::Canceled,
+        Ok(_) => JobState::Completed,
+        Err(_) => JobState::Failed,
+    };
+
+    db.finish_job_report(&report).await?;
+
+    result?;
+
+    Ok(report)
+}
+
+/// Ingests every `.md` member of a `.tar`/`.tar.gz`/`.zip` archive without
+/// ever writing it to disk: members are streamed into memory, nested
+/// directories in the archive become nested `Directory` rows (mirroring
+/// `document::discover`), and the bytes are parsed with
+/// `DocumentMeta::read_from_str` via `Document::from_bytes`. Directory stats
+/// are rolled up the same way a filesystem scan rolls them up, and members
+/// that disappeared from a re-ingested archive are pruned the same way a
+/// rescanned directory prunes deleted files.
+async fn process_archive(db: &Database, path: &Path, job: &Job) -> Result<(), KnawledgeError> {
+    let entries = read_entries(path)?;
+
+    job.record_discovered(entries.len() as i64);
+
+    let archive_name = Document::name(path);
+    // Identifies this archive across the whole DB, unlike `archive_name`
+    // (its bare basename, which two distinct archives can easily share) -
+    // used both to key its root `Directory` row and to namespace its
+    // members' document paths so they can't collide with another archive's.
+    let archive_path = path.canonicalize()?.display().to_string();
+    let mut dirs: HashMap<PathBuf, uuid::Uuid> = HashMap::new();
+    let mut parents: HashMap<uuid::Uuid, Option<uuid::Uuid>> = HashMap::new();
+    let mut names_by_dir: HashMap<uuid::Uuid, Vec<String>> = HashMap::new();
+
+    let mut work = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if job.is_cancelled() {
+            break;
+        }
+
+        let directory = ensure_directory_chain(
+            db,
+            &archive_name,
+            &archive_path,
+            entry.relative.parent(),
+            &mut dirs,
+            &mut parents,
+        )
+        .await?;
+
+        let name = Document::name(&entry.relative);
+        names_by_dir.entry(directory).or_default().push(name);
+
+        work.push((directory, entry.relative, entry.bytes));
+    }
+
+    let mut removed = 0;
+    for &directory in dirs.values() {
+        let names = names_by_dir.get(&directory).map(Vec::as_slice).unwrap_or(&[]);
+        let removed_names: Vec<String> = db
+            .list_document_names_in_dir(directory)
+            .await?
+            .into_iter()
+            .filter(|name| !names.contains(name))
+            .collect();
+
+        for name in &removed_names {
+            db.delete_doc_by_name(directory, name).await?;
+        }
+        removed += removed_names.len();
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    tokio::task::block_in_place(|| {
+        work.into_par_iter()
+            .for_each_with(tx, |tx, (directory, relative, bytes)| {
+                if job.is_cancelled() {
+                    return;
+                }
+
+                let name = Document::name(&relative);
+                // Namespaced under the archive's own identity so the same
+                // in-archive path (e.g. `README.md`) from two different
+                // archives can't collide in the global `get_doc_by_path`
+                // lookup below.
+                let doc_path = Path::new(&archive_path).join(&relative).display().to_string();
+                let result = Document::from_bytes(directory, name, doc_path, bytes);
+                let _ = tx.send(result);
+            });
+    });
+
+    let mut direct_stats: HashMap<uuid::Uuid, document::DirStats> = HashMap::new();
+
+    for result in rx {
+        match result {
+            Ok((file, meta)) => {
+                job.record_processed(1);
+
+                let stats = direct_stats.entry(file.directory).or_default();
+                stats.doc_count += 1;
+                stats.reading_time += meta.reading_time.unwrap_or(0);
+                if let Some(tags) = &meta.tags {
+                    stats.tags.extend(tags.iter().cloned());
+                }
+
+                match db.get_doc_by_path(&file.path).await? {
+                    Some(existing) if existing.hash == file.hash => {
+                        debug!("No content change for {}", file.path);
+                    }
+                    Some(_) => db.update_doc(&file, &meta).await?,
+                    None => db.insert_doc(&file, &meta).await?,
+                }
+            }
+            Err(e) => error!("Error occurred while processing archive member: {e:?}"),
+        }
+    }
+
+    document::persist_directory_stats(db, &parents, &direct_stats).await?;
+
+    debug!("Archive {archive_name}: removed {removed} stale document(s)");
+
+    Ok(())
+}
+
+/// Ensures a `Directory` row exists for every path component leading up to
+/// `relative_parent`, creating the archive's own root directory lazily along
+/// the way, and returns the innermost one's id. The root is keyed by
+/// `archive_path` (the archive's full, canonicalized source path) rather than
+/// its basename, so two archives uploaded under the same filename don't get
+/// merged into one root directory. Every directory created or looked up this
+/// way records its parent in `parents`, so the caller can roll up stats the
+/// same way a filesystem scan does.
+async fn ensure_directory_chain(
+    db: &Database,
+    archive_name: &str,
+    archive_path: &str,
+    relative_parent: Option<&Path>,
+    dirs: &mut HashMap<PathBuf, uuid::Uuid>,
+    parents: &mut HashMap<uuid::Uuid, Option<uuid::Uuid>>,
+) -> Result<uuid::Uuid, KnawledgeError> {
+    let root_key = PathBuf::new();
+    let root_id = match dirs.get(&root_key) {
+        Some(id) => *id,
+        None => {
+            let root = match db.get_dir_by_path(archive_path).await? {
+                Some(dir) => dir,
+                None => db.insert_dir(archive_path, archive_name, None).await?,
+            };
+            dirs.insert(root_key, root.id);
+            parents.insert(root.id, root.parent);
+            root.id
+        }
+    };
+
+    let Some(relative_parent) = relative_parent else {
+        return Ok(root_id);
+    };
+
+    let mut current_id = root_id;
+    let mut current_path = PathBuf::new();
+
+    for component in relative_parent.components() {
+        let Component::Normal(part) = component else {
+            continue;
+        };
+        current_path.push(part);
+
+        if let Some(id) = dirs.get(&current_path) {
+            current_id = *id;
+            continue;
+        }
+
+        let name = part.to_str().unwrap_or("__unknown");
+        let full_path = current_path.display().to_string();
+
+        let dir = match db.get_dir_by_name_and_parent(name, current_id).await? {
+            Some(dir) => dir,
+            None => db.insert_dir(&full_path, name, Some(current_id)).await?,
+        };
+
+        dirs.insert(current_path.clone(), dir.id);
+        parents.insert(dir.id, dir.parent);
+        current_id = dir.id;
+    }
+
+    Ok(current_id)
+}
+
+fn read_entries(path: &Path) -> Result<Vec<ArchiveEntry>, KnawledgeError> {
+    if path.to_string_lossy().ends_with(".zip") {
+        read_zip(path)
+    } else {
+        read_tar(path)
+    }
+}
+
+fn is_markdown(path: &Path) -> bool {
+    path.extension().and_then(OsStr::to_str) == Some("md")
+}
+
+fn read_tar(path: &Path) -> Result<Vec<ArchiveEntry>, KnawledgeError> {
+    let file = fs::File::open(path)?;
+    let name = path.to_string_lossy();
+
+    let reader: Box<dyn Read> = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = Vec::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let relative = entry.path()?.into_owned();
+        if !is_markdown(&relative) {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+
+        entries.push(ArchiveEntry { relative, bytes });
+    }
+
+    Ok(entries)
+}
+
+fn read_zip(path: &Path) -> Result<Vec<ArchiveEntry>, KnawledgeError> {
+    let file = fs::File::open(path)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut entries = Vec::new();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        let Some(relative) = entry.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+        if !is_markdown(&relative) {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+
+        entries.push(ArchiveEntry { relative, bytes });
+    }
+
+    Ok(entries)
+}
@@ -1,16 +1,15 @@
-use async_recursion::async_recursion;
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info};
 
 use crate::db::Database;
 use crate::error::KnawledgeError;
-use crate::{FILES_PER_THREAD, MAX_THREADS};
+use crate::job::{self, Job, JobReport, JobState};
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::PathBuf;
-use std::thread::ScopedJoinHandle;
-use std::time::Instant;
 use std::{fmt::Debug, path::Path};
 
 /// Database model
@@ -22,6 +21,9 @@ pub struct Document {
     pub directory: uuid::Uuid,
     /// Canonicalised path
     pub path: String,
+    /// BLAKE3 digest (hex) of the file's contents, used to detect edits
+    /// without relying on mtimes.
+    pub hash: String,
 }
 
 impl Document {
@@ -31,13 +33,30 @@ impl Document {
         path: String,
     ) -> Result<(Self, DocumentMeta), KnawledgeError> {
         debug!("Processing {path}");
+        let bytes = fs::read(&path)?;
+        Self::from_bytes(directory, name, path, bytes)
+    }
+
+    /// Builds a `Document` and its parsed [`DocumentMeta`] directly from
+    /// already-read bytes, without touching the filesystem. Shared by the fs
+    /// walker (which reads the bytes off disk first) and archive ingestion
+    /// (which reads them straight out of a tar/zip member).
+    pub fn from_bytes(
+        directory: uuid::Uuid,
+        name: String,
+        path: String,
+        bytes: Vec<u8>,
+    ) -> Result<(Self, DocumentMeta), KnawledgeError> {
+        let hash = blake3::hash(&bytes).to_hex().to_string();
 
-        let meta = DocumentMeta::read_from_file(&path)?;
+        let content = String::from_utf8(bytes)?;
+        let (meta, _) = DocumentMeta::read_from_str(&content)?;
 
         let document = Self {
             file_name: name,
             directory,
             path,
+            hash,
         };
 
         Ok((document, meta))
@@ -130,7 +149,7 @@ impl DocumentMeta {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct Directory {
     pub id: uuid::Uuid,
     pub name: String,
@@ -138,220 +157,409 @@ pub struct Directory {
     pub path: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Markdown documents directly inside this directory.
+    pub doc_count_direct: i64,
+    /// Markdown documents inside this directory or any of its descendants.
+    pub doc_count_recursive: i64,
+    /// Summed `reading_time` across this directory and its descendants.
+    pub reading_time_total: i32,
+    /// Union of `tags` across this directory and its descendants.
+    pub tags: Vec<String>,
 }
 
-#[async_recursion]
-pub async fn process_directory(
-    db: &Database,
-    path: impl AsRef<Path> + 'async_recursion + Send,
-    parent: Option<uuid::Uuid>,
-) -> Result<(), KnawledgeError> {
-    let entries = fs::read_dir(&path)?
-        .filter_map(Result::ok)
-        .collect::<Vec<_>>();
+/// Aggregate stats rolled up for a single directory during a scan.
+///
+/// Shared between the fs walker and archive ingestion, so both paths feed the
+/// same roll-up in [`persist_directory_stats`] instead of each growing their
+/// own copy.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct DirStats {
+    pub(crate) doc_count: i64,
+    pub(crate) reading_time: i32,
+    pub(crate) tags: std::collections::HashSet<String>,
+}
 
-    let full_path = path.as_ref().canonicalize()?.display().to_string();
-    let dir_name = path
-        .as_ref()
-        .file_name()
-        .ok_or(KnawledgeError::InvalidDirectory(format!(
-            "{full_path}: unsupported directory"
-        )))?;
-
-    debug!("Loading {full_path}");
-
-    let dir_name = dir_name
-        .to_str()
-        .ok_or(KnawledgeError::InvalidDirectory(format!(
-            "{dir_name:?}: not valid utf-8"
-        )))?;
-
-    let directory_entry: Directory = match parent {
-        Some(parent_id) => {
-            let parent = db.get_dir_by_name_and_parent(dir_name, parent_id).await?;
-
-            match parent {
-                Some(dir) => dir,
-                None => db.insert_dir(&full_path, dir_name, Some(parent_id)).await?,
-            }
-        }
-        None => {
-            let root = db.get_root_dir_by_name(dir_name).await?;
-            match root {
-                Some(dir) => dir,
-                None => db.insert_dir(&full_path, dir_name, None).await?,
-            }
-        }
+/// Runs [`process_directory`] as an observable, cancellable [`Job`].
+///
+/// Persists a `queued`/`running` [`JobReport`] before the scan starts, registers
+/// the job so `job::request_cancellation` can reach it, then finalizes the
+/// report as `completed`, `failed` or `canceled` once the scan returns.
+pub async fn process_directory_job(
+    db: &Database,
+    path: impl AsRef<Path> + Send,
+    job: &Job,
+) -> Result<JobReport, KnawledgeError> {
+    let mut report = JobReport::queued(job.id);
+    report.state = JobState::Running;
+    db.insert_job_report(&report).await?;
+
+    job::register(job);
+    let result = process_directory(db, path, None, job).await;
+    job::unregister(&job.id);
+
+    let (discovered, processed) = job.progress();
+    report.files_discovered = discovered;
+    report.files_processed = processed;
+    report.finished_at = Some(Utc::now());
+    report.state = match &result {
+        _ if job.is_cancelled() => JobState::Canceled,
+        Ok(_) => JobState::Completed,
+        Err(_) => JobState::Failed,
     };
 
-    for entry in entries.iter() {
-        if entry.path().is_dir() {
-            process_directory(db, entry.path(), Some(directory_entry.id)).await?;
-        }
-    }
-
-    let mut files_processed = vec![];
-    let mut markdown_files = vec![];
-    let mut file_names = vec![];
-
-    for entry in entries.iter() {
-        let path = entry.path();
-        let Some(ext) = path.extension() else {
-            continue;
-        };
+    db.finish_job_report(&report).await?;
 
-        let Some(ext) = ext.to_str() else {
-            continue;
-        };
+    result?;
 
-        if ext != "md" {
-            continue;
-        }
+    Ok(report)
+}
 
-        if let Some(name) = path.file_name() {
-            if let Some(name) = name.to_str() {
-                file_names.push(name.to_string());
-            }
-        }
-        markdown_files.push(path);
+/// Discovers a directory tree and parses any markdown files found in it,
+/// upserting the results into the database.
+///
+/// Discovery walks the tree on the rayon pool (see [`walk_fs`]), then creates
+/// or looks up the `Directory` rows (cheap, I/O-bound) in a single top-down
+/// pass while collecting `(directory_id, path)` work items for every markdown
+/// file found. Parsing then runs once over the whole flattened list on the
+/// rayon work-stealing pool, so a handful of
+/// large files and a pile of tiny ones balance across cores instead of being
+/// pinned to a pre-computed batch.
+pub async fn process_directory(
+    db: &Database,
+    path: impl AsRef<Path> + Send,
+    parent: Option<uuid::Uuid>,
+    job: &Job,
+) -> Result<(), KnawledgeError> {
+    let mut work = Vec::new();
+    let mut hashes = HashMap::new();
+    let mut removed = 0;
+    let mut parents = HashMap::new();
+
+    discover(
+        db, path, parent, job, &mut work, &mut hashes, &mut removed, &mut parents,
+    )
+    .await?;
+
+    job.record_discovered(work.len() as i64);
+
+    let (amt_unchanged, amt_updated, amt_new, direct_stats) =
+        parse_and_store(db, work, &hashes, job).await?;
+
+    persist_directory_stats(db, &parents, &direct_stats).await?;
+
+    // `parents` only covers directories visited by this call's `discover`, so
+    // for a subtree scan (a notifier-driven `process_directory` on a single
+    // new directory, for instance) it stops one level short of the true
+    // ancestor chain. Walk the rest of the way up from the database instead
+    // of relying on a map that was never meant to cover it.
+    if let Some(parent_id) = parent {
+        recompute_directory_stats_chain(db, parent_id).await?;
     }
 
-    let existing = db
-        .list_document_in_dir(directory_entry.id, &file_names)
-        .await?;
+    let (discovered, processed) = job.progress();
+    db.update_job_progress(job.id, discovered, processed).await?;
+
+    info!(
+        "Unchanged: {amt_unchanged} Updated: {amt_updated} New: {amt_new} Removed: {removed}",
+    );
 
-    let mut amt_files_existing = 0;
-    for item in existing {
-        let idx = markdown_files.iter().position(|el| {
-            let Some(file_name) = el.iter().last() else {
-                return false;
-            };
+    Ok(())
+}
 
-            let Some(file_name) = file_name.to_str() else {
-                return false;
-            };
+/// Walks `root` and every subdirectory beneath it on the rayon work-stealing
+/// pool instead of one `fs::read_dir` per `.await`, jwalk-style: a directory
+/// fans out into a parallel task per subdirectory, each returning its own
+/// `(directories, files)` lists, which are then flattened back together.
+/// Directories come back in parent-before-child order (every subtree's own
+/// root is pushed ahead of its children), so callers can create `Directory`
+/// rows in a single top-down pass with no extra bookkeeping.
+fn walk_fs(root: PathBuf) -> Result<(Vec<PathBuf>, Vec<(PathBuf, PathBuf)>), KnawledgeError> {
+    let entries = fs::read_dir(&root)?
+        .filter_map(Result::ok)
+        .collect::<Vec<_>>();
 
-            item.file_name == file_name
-        });
+    let mut dirs = vec![root.clone()];
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
 
-        if let Some(idx) = idx {
-            debug!("Already exists: {}", item.file_name);
-            markdown_files.swap_remove(idx);
-            amt_files_existing += 1;
+    for entry in entries {
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+            subdirs.push(entry_path);
+        } else if entry_path.extension().and_then(OsStr::to_str) == Some("md") {
+            files.push((root.clone(), entry_path));
         }
     }
 
-    process_files(directory_entry.id, markdown_files, &mut files_processed)?;
+    let sub_results: Vec<Result<(Vec<PathBuf>, Vec<(PathBuf, PathBuf)>), KnawledgeError>> =
+        subdirs.into_par_iter().map(walk_fs).collect();
 
-    let amt_files_processed = files_processed.len();
-    for (file, meta) in files_processed {
-        db.insert_doc(&file, &meta).await?;
+    for sub_result in sub_results {
+        let (sub_dirs, sub_files) = sub_result?;
+        dirs.extend(sub_dirs);
+        files.extend(sub_files);
     }
 
-    info!(
-        "{full_path} - Existing files: {amt_files_existing} Processed files: {amt_files_processed}",
-    );
-
-    Ok(())
+    Ok((dirs, files))
 }
 
-fn process_files(
-    directory: uuid::Uuid,
-    file_paths: Vec<PathBuf>,
-    files: &mut Vec<(Document, DocumentMeta)>,
+/// Discovers `path` and every directory beneath it, ensuring a `Directory`
+/// row exists for each one, and accumulates markdown work items, each
+/// directory's existing file-name -> hash map (for the change-detection pass
+/// that follows parsing), and each directory's parent (for the stats roll-up
+/// that follows it). Already-deleted files are pruned from the DB as their
+/// directory is visited.
+///
+/// The filesystem walk itself runs on [`walk_fs`] before any of this, so the
+/// I/O-bound part of discovery is parallel; only the `Directory` row
+/// bookkeeping below, which is cheap and needs a consistent view of already-
+/// created parents, stays sequential.
+async fn discover(
+    db: &Database,
+    path: impl AsRef<Path> + Send,
+    parent: Option<uuid::Uuid>,
+    job: &Job,
+    work: &mut Vec<(uuid::Uuid, PathBuf)>,
+    hashes: &mut HashMap<uuid::Uuid, HashMap<String, String>>,
+    removed: &mut usize,
+    parents: &mut HashMap<uuid::Uuid, Option<uuid::Uuid>>,
 ) -> Result<(), KnawledgeError> {
-    let files_total = file_paths.len();
-
-    let mut files_remaining = files_total;
+    if job.is_cancelled() {
+        return Ok(());
+    }
 
-    while files_remaining > 0 {
-        let mut batches: Vec<&[PathBuf]> = vec![&[]; *MAX_THREADS];
+    let root = path.as_ref().to_path_buf();
+    let (all_dirs, all_files) = {
+        let root = root.clone();
+        tokio::task::block_in_place(move || walk_fs(root))?
+    };
 
-        for (i, batch) in batches.iter_mut().enumerate() {
-            let start = i * FILES_PER_THREAD;
+    let mut dir_ids: HashMap<PathBuf, uuid::Uuid> = HashMap::new();
 
-            let mut end = (i + 1) * FILES_PER_THREAD;
+    for dir_path in &all_dirs {
+        if job.is_cancelled() {
+            return Ok(());
+        }
 
-            if end > files_total {
-                end = files_total;
+        let full_path = dir_path.canonicalize()?.display().to_string();
+        let dir_name = dir_path
+            .file_name()
+            .ok_or(KnawledgeError::InvalidDirectory(format!(
+                "{full_path}: unsupported directory"
+            )))?;
+        let dir_name = dir_name
+            .to_str()
+            .ok_or(KnawledgeError::InvalidDirectory(format!(
+                "{dir_name:?}: not valid utf-8"
+            )))?;
 
-                *batch = &file_paths[start..end];
+        debug!("Loading {full_path}");
 
-                files_remaining -= end - start;
+        let parent_id = if dir_path == &root {
+            parent
+        } else {
+            dir_path.parent().and_then(|p| dir_ids.get(p).copied())
+        };
 
-                break;
+        let directory_entry: Directory = match parent_id {
+            Some(parent_id) => {
+                let existing = db.get_dir_by_name_and_parent(dir_name, parent_id).await?;
+                match existing {
+                    Some(dir) => dir,
+                    None => db.insert_dir(&full_path, dir_name, Some(parent_id)).await?,
+                }
+            }
+            None => {
+                let root = db.get_root_dir_by_name(dir_name).await?;
+                match root {
+                    Some(dir) => dir,
+                    None => db.insert_dir(&full_path, dir_name, None).await?,
+                }
             }
+        };
+
+        parents.insert(directory_entry.id, directory_entry.parent);
+        dir_ids.insert(dir_path.clone(), directory_entry.id);
+    }
 
-            *batch = &file_paths[start..end];
+    let mut files_by_dir: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for (dir_path, file_path) in all_files {
+        files_by_dir.entry(dir_path).or_default().push(file_path);
+    }
 
-            files_remaining -= FILES_PER_THREAD;
+    for dir_path in &all_dirs {
+        if job.is_cancelled() {
+            return Ok(());
         }
 
-        type TaskWithStart<'a> = (
-            ScopedJoinHandle<'a, Result<Vec<(Document, DocumentMeta)>, KnawledgeError>>,
-            Instant,
-        );
+        let directory_id = dir_ids[dir_path];
+        let markdown_files = files_by_dir.remove(dir_path).unwrap_or_default();
+
+        let file_names: Vec<String> = markdown_files
+            .iter()
+            .filter_map(|path| path.file_name().and_then(OsStr::to_str))
+            .map(str::to_string)
+            .collect();
+
+        // Indexed by file name rather than content, so an edited file is
+        // caught by the change-detection pass instead of being skipped
+        // outright.
+        let existing_hashes: HashMap<String, String> = db
+            .list_document_in_dir(directory_id, &file_names)
+            .await?
+            .into_iter()
+            .map(|doc| (doc.file_name, doc.hash))
+            .collect();
+
+        let removed_names: Vec<String> = db
+            .list_document_names_in_dir(directory_id)
+            .await?
+            .into_iter()
+            .filter(|name| !file_names.contains(name))
+            .collect();
+
+        for name in &removed_names {
+            db.delete_doc_by_name(directory_id, name).await?;
+        }
+        *removed += removed_names.len();
 
-        batches.retain(|batch| !batch.is_empty());
+        hashes.insert(directory_id, existing_hashes);
+        work.extend(markdown_files.into_iter().map(|path| (directory_id, path)));
+    }
 
-        if batches.len() > 1 {
-            debug!("Processing multiple batches");
-            std::thread::scope(|scope| {
-                let mut tasks: Vec<TaskWithStart> = Vec::with_capacity(*MAX_THREADS);
+    Ok(())
+}
 
-                for batch in batches {
-                    if batch.is_empty() {
-                        continue;
-                    }
+/// Parses every `(directory_id, path)` work item on the rayon work-stealing
+/// pool, then compares each result against the hashes collected during
+/// discovery and upserts it on the async side. Returns the
+/// (unchanged, updated, new) counts along with each directory's direct stats.
+async fn parse_and_store(
+    db: &Database,
+    work: Vec<(uuid::Uuid, PathBuf)>,
+    hashes: &HashMap<uuid::Uuid, HashMap<String, String>>,
+    job: &Job,
+) -> Result<(usize, usize, usize, HashMap<uuid::Uuid, DirStats>), KnawledgeError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    tokio::task::block_in_place(|| {
+        work.into_par_iter()
+            .for_each_with(tx, |tx, (directory, path)| {
+                if job.is_cancelled() {
+                    return;
+                }
 
-                    let task = scope.spawn(move || {
-                        let mut files = vec![];
-                        for file_path in batch {
-                            let file = Document::new(
-                                directory,
-                                Document::name(file_path.canonicalize()?),
-                                file_path.display().to_string(),
-                            )?;
-                            files.push(file);
-                        }
-                        Ok(files)
-                    });
-
-                    debug!("Spawned thread {:?}", task.thread().id());
-
-                    tasks.push((task, Instant::now()));
+                let name = Document::name(&path);
+                let result = Document::new(directory, name, path.display().to_string());
+                let _ = tx.send(result);
+            });
+    });
+
+    let mut amt_unchanged = 0;
+    let mut amt_updated = 0;
+    let mut amt_new = 0;
+    let mut direct_stats: HashMap<uuid::Uuid, DirStats> = HashMap::new();
+
+    for result in rx {
+        match result {
+            Ok((file, meta)) => {
+                job.record_processed(1);
+
+                let stats = direct_stats.entry(file.directory).or_default();
+                stats.doc_count += 1;
+                stats.reading_time += meta.reading_time.unwrap_or(0);
+                if let Some(tags) = &meta.tags {
+                    stats.tags.extend(tags.iter().cloned());
                 }
 
-                for (task, start) in tasks {
-                    let id = task.thread().id();
-                    let result = task.join();
-                    match result {
-                        Ok(Ok(processed)) => {
-                            files.extend(processed);
-                            debug!(
-                                "Thread {:?} finished in {}ms",
-                                id,
-                                Instant::now().duration_since(start).as_nanos() as f32 * 0.001
-                            );
-                        }
-                        Ok(Err(e)) => error!("Error occurred while processing files: {e:?}"),
-                        Err(e) => error!("Error occurred while processing files: {e:?}"),
+                let stored = hashes
+                    .get(&file.directory)
+                    .and_then(|dir_hashes| dir_hashes.get(&file.file_name));
+
+                match stored {
+                    Some(stored_hash) if *stored_hash == file.hash => amt_unchanged += 1,
+                    Some(_) => {
+                        debug!("Contents changed: {}", file.file_name);
+                        amt_updated += 1;
+                        db.update_doc(&file, &meta).await?;
+                    }
+                    None => {
+                        amt_new += 1;
+                        db.insert_doc(&file, &meta).await?;
                     }
                 }
-            });
-        } else {
-            debug!("Processing single batch");
-            for file_path in batches[0] {
-                let file = Document::new(
-                    directory,
-                    Document::name(file_path),
-                    file_path.canonicalize()?.display().to_string(),
-                )?;
-                files.push(file);
             }
+            Err(e) => error!("Error occurred while processing files: {e:?}"),
+        }
+    }
+
+    Ok((amt_unchanged, amt_updated, amt_new, direct_stats))
+}
+
+/// Rolls each directory's direct stats up through its ancestors so a parent
+/// reflects all of its descendants, then persists both the direct and
+/// recursive doc counts alongside the recursive reading-time sum and tag
+/// union for every directory visited this scan.
+///
+/// Shared by the fs walker and archive ingestion: both build their own
+/// `parents`/`direct` maps while discovering their own kind of source, then
+/// hand them here to do the actual roll-up and write.
+pub(crate) async fn persist_directory_stats(
+    db: &Database,
+    parents: &HashMap<uuid::Uuid, Option<uuid::Uuid>>,
+    direct: &HashMap<uuid::Uuid, DirStats>,
+) -> Result<(), KnawledgeError> {
+    let mut recursive: HashMap<uuid::Uuid, DirStats> = direct.clone();
+
+    for (dir_id, stats) in direct {
+        let mut ancestor = parents.get(dir_id).copied().flatten();
+        while let Some(ancestor_id) = ancestor {
+            let entry = recursive.entry(ancestor_id).or_default();
+            entry.doc_count += stats.doc_count;
+            entry.reading_time += stats.reading_time;
+            entry.tags.extend(stats.tags.iter().cloned());
+            ancestor = parents.get(&ancestor_id).copied().flatten();
         }
     }
 
+    for dir_id in parents.keys() {
+        let direct_count = direct.get(dir_id).map(|s| s.doc_count).unwrap_or(0);
+        let totals = recursive.get(dir_id).cloned().unwrap_or_default();
+        let mut tags: Vec<String> = totals.tags.into_iter().collect();
+        tags.sort_unstable();
+
+        db.update_directory_stats(
+            *dir_id,
+            direct_count,
+            totals.doc_count,
+            totals.reading_time,
+            &tags,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Recomputes and persists stats for `directory_id` and every ancestor above
+/// it, walking the `parent` chain through the database rather than a
+/// scan-local map. Unlike [`persist_directory_stats`], which rolls up the
+/// direct stats a single scan collected, this re-derives each directory's
+/// numbers from what's actually stored for it, so it stays correct no matter
+/// how little of the tree the triggering change touched. Used for anything
+/// that isn't a full root scan: a notifier-driven insert/update/delete/rename,
+/// or the part of a subtree scan above where `discover` started.
+pub(crate) async fn recompute_directory_stats_chain(
+    db: &Database,
+    directory_id: uuid::Uuid,
+) -> Result<(), KnawledgeError> {
+    let mut current = Some(directory_id);
+
+    while let Some(dir_id) = current {
+        db.recompute_directory_stats(dir_id).await?;
+        current = db.get_directory(dir_id).await?.and_then(|dir| dir.parent);
+    }
+
     Ok(())
 }
 
@@ -35,6 +35,9 @@ pub enum KnawledgeError {
 
     #[error("YAML error: {0}")]
     SerdeYaml(#[from] serde_yaml::Error),
+
+    #[error("Archive error: {0}")]
+    Zip(#[from] zip::result::ZipError),
 }
 
 impl IntoResponse for KnawledgeError {
@@ -56,6 +59,9 @@ impl IntoResponse for KnawledgeError {
                 (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
             }
             KnawledgeError::DoesNotExist(e) => (StatusCode::NOT_FOUND, e).into_response(),
+            KnawledgeError::Zip(e) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+            }
             e => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
         }
     }
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::error::KnawledgeError;
+
+/// Lifecycle state of an indexing [`Job`], persisted alongside its [`JobReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Canceled,
+}
+
+/// A handle to a directory scan in progress.
+///
+/// Cloning a [`Job`] shares the same cancellation flag and progress counters,
+/// so the handle registered for the duration of a scan and the one a cancel
+/// route looks up by id are always looking at the same state.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: Uuid,
+    cancelled: Arc<AtomicBool>,
+    discovered: Arc<AtomicI64>,
+    processed: Arc<AtomicI64>,
+}
+
+impl Job {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            discovered: Arc::new(AtomicI64::new(0)),
+            processed: Arc::new(AtomicI64::new(0)),
+        }
+    }
+
+    /// Signals the running job to stop at its next cancellation checkpoint.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn record_discovered(&self, amount: i64) {
+        self.discovered.fetch_add(amount, Ordering::SeqCst);
+    }
+
+    pub fn record_processed(&self, amount: i64) {
+        self.processed.fetch_add(amount, Ordering::SeqCst);
+    }
+
+    /// Current (files discovered, files processed) counters.
+    pub fn progress(&self) -> (i64, i64) {
+        (
+            self.discovered.load(Ordering::SeqCst),
+            self.processed.load(Ordering::SeqCst),
+        )
+    }
+}
+
+impl Default for Job {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Persisted row tracking a [`Job`]'s progress, returned to clients polling for status.
+#[derive(Debug, Serialize)]
+pub struct JobReport {
+    pub id: Uuid,
+    pub state: JobState,
+    pub files_discovered: i64,
+    pub files_processed: i64,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl JobReport {
+    pub fn queued(id: Uuid) -> Self {
+        Self {
+            id,
+            state: JobState::Queued,
+            files_discovered: 0,
+            files_processed: 0,
+            started_at: Utc::now(),
+            finished_at: None,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Handles for jobs currently running in this process, keyed by id.
+    ///
+    /// Lets the cancel route flip a flag that the scan loop driving that job
+    /// is actively polling, without threading a channel through every call.
+    static ref ACTIVE_JOBS: Mutex<HashMap<Uuid, Job>> = Mutex::new(HashMap::new());
+}
+
+/// Registers a job as active so it can be looked up for cancellation.
+pub fn register(job: &Job) {
+    ACTIVE_JOBS.lock().unwrap().insert(job.id, job.clone());
+}
+
+/// Removes a job from the active set once its scan has finished.
+pub fn unregister(id: &Uuid) {
+    ACTIVE_JOBS.lock().unwrap().remove(id);
+}
+
+/// Flags an active job for cancellation. Returns `false` if no job with that
+/// id is currently running.
+pub fn request_cancellation(id: &Uuid) -> bool {
+    match ACTIVE_JOBS.lock().unwrap().get(id) {
+        Some(job) => {
+            job.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// `GET /jobs` - lists every job report, running or finished.
+pub async fn list_jobs(
+    axum::extract::State(state): axum::extract::State<crate::state::State>,
+) -> Result<axum::Json<Vec<JobReport>>, KnawledgeError> {
+    let reports = state.db.list_job_reports().await?;
+    Ok(axum::Json(reports))
+}
+
+/// `POST /jobs/:id/cancel` - requests cancellation of a running job.
+///
+/// Cancellation is cooperative: the scan only checks the flag between
+/// directories during discovery and before parsing each file, so it can take
+/// a moment to actually stop.
+pub async fn cancel_job(
+    axum::extract::Path(id): axum::extract::Path<Uuid>,
+    axum::extract::State(state): axum::extract::State<crate::state::State>,
+) -> Result<axum::http::StatusCode, KnawledgeError> {
+    if !request_cancellation(&id) {
+        return Err(KnawledgeError::NotFound(format!("job {id}")));
+    }
+    state.db.mark_job_canceling(id).await?;
+    Ok(axum::http::StatusCode::ACCEPTED)
+}
@@ -1,21 +1,20 @@
 use clap::Parser;
-use std::{collections::HashSet, num::NonZeroUsize};
-use tracing::{info, Level};
+use std::collections::HashSet;
+use tracing::{error, info, Level};
 
-use crate::{config::Config, db::Database, document::process_directory, state::State};
-
-pub const FILES_PER_THREAD: usize = 128;
-
-lazy_static::lazy_static! {
-    pub static ref MAX_THREADS: usize = std::thread::available_parallelism().unwrap_or(NonZeroUsize::new(1).unwrap()).into();
-}
+use crate::{
+    config::Config, db::Database, document::process_directory_job, job::Job,
+    notifiy::NotifyHandler, state::State,
+};
 
+pub mod archive;
 pub mod chunk;
 pub mod config;
 pub mod db;
 pub mod document;
 pub mod error;
 pub mod htmx;
+pub mod job;
 pub mod notifiy;
 pub mod router;
 pub mod state;
@@ -43,26 +42,43 @@ async fn main() {
         .await
         .expect("could not trim directories");
 
+    // Jobs that were still `running` when the process died last time never
+    // get a finishing event from the loop below, which always starts a fresh
+    // `Job` with its own id per configured directory rather than resuming the
+    // old one. Mark them `canceled` so they land in a terminal state instead
+    // of sitting as permanently-stuck `running` rows in `GET /jobs`.
+    database
+        .cancel_stale_running_jobs()
+        .await
+        .expect("unable to cancel stale jobs");
+
     for dir in config.directories.iter() {
-        process_directory(&database, dir, None)
-            .await
-            .expect("unable to process directory");
+        let job = Job::new();
+        let result = if archive::is_archive(dir) {
+            archive::process_archive_job(&database, dir, &job).await
+        } else {
+            process_directory_job(&database, dir, &job).await
+        };
+        match result {
+            Ok(report) => info!("Job {} finished: {:?}", report.id, report.state),
+            Err(e) => error!("Job {} failed: {e}", job.id),
+        }
     }
 
-    // let (tx, rx) = std::sync::mpsc::channel();
+    let (tx, rx) = std::sync::mpsc::channel();
 
-    // let roots = database
-    //     .list_root_paths()
-    //     .await
-    //     .expect("unable to process roots")
-    //     .into_iter()
-    //     .collect::<HashSet<_>>();
+    let roots = database
+        .list_root_paths()
+        .await
+        .expect("unable to process roots")
+        .into_iter()
+        .collect::<HashSet<_>>();
 
-    // let notifier = NotifyHandler::new(database.clone(), roots, rx);
+    let notifier = NotifyHandler::new(database.clone(), roots, rx);
 
-    // let handle = notifier.run().expect("could not start watcher");
+    let handle = notifier.run().expect("could not start watcher");
 
-    // let handle = NotifierHandle { tx, handle };
+    let _watcher = notifiy::NotifierHandle { tx, handle };
 
     let state = State::new(database.clone(), config);
 
@@ -0,0 +1,244 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{debug, error, info, warn};
+
+use crate::db::Database;
+use crate::document::Document;
+use crate::error::KnawledgeError;
+
+/// How long to let a burst of events settle before acting on it, so an
+/// editor's "write then rename" save sequence collapses into one update
+/// instead of a delete followed by a create.
+const DEBOUNCE_WINDOW: Duration = Duration::from_millis(200);
+
+/// Owned by `main` for as long as the watcher should run. Dropping the
+/// sender (or sending on it) tells the background task to stop.
+pub struct NotifierHandle {
+    pub tx: mpsc::Sender<()>,
+    pub handle: tokio::task::JoinHandle<()>,
+}
+
+/// Mirrors filesystem changes under the configured root directories into the
+/// database, so edits made outside of a scan show up without a restart.
+pub struct NotifyHandler {
+    db: Database,
+    roots: HashSet<String>,
+    shutdown: mpsc::Receiver<()>,
+}
+
+impl NotifyHandler {
+    pub fn new(db: Database, roots: HashSet<String>, shutdown: mpsc::Receiver<()>) -> Self {
+        Self {
+            db,
+            roots,
+            shutdown,
+        }
+    }
+
+    /// Starts watching every root and spawns the debounced event loop as a
+    /// background tokio task running alongside `axum::serve`.
+    pub fn run(self) -> Result<tokio::task::JoinHandle<()>, KnawledgeError> {
+        let (event_tx, event_rx) = mpsc::channel();
+
+        let mut watcher: RecommendedWatcher = notify::recommended_watcher(event_tx)?;
+        for root in &self.roots {
+            watcher.watch(Path::new(root), RecursiveMode::Recursive)?;
+        }
+
+        info!("Watching {} root(s) for changes", self.roots.len());
+
+        let handle = tokio::spawn(async move {
+            // Kept alive for the lifetime of the task; dropping it stops the watch.
+            let _watcher = watcher;
+            self.event_loop(event_rx).await;
+        });
+
+        Ok(handle)
+    }
+
+    async fn event_loop(mut self, event_rx: mpsc::Receiver<notify::Result<Event>>) {
+        // Keyed by the event's full path list rather than one entry per path,
+        // so a rename-both event (2 paths, 1 event) lands under a single key
+        // instead of being inserted once per path and applied twice.
+        let mut pending: HashMap<Vec<PathBuf>, Event> = HashMap::new();
+
+        loop {
+            if !matches!(self.shutdown.try_recv(), Err(mpsc::TryRecvError::Empty)) {
+                info!("Watcher shutting down");
+                return;
+            }
+
+            let received =
+                tokio::task::block_in_place(|| event_rx.recv_timeout(DEBOUNCE_WINDOW));
+
+            match received {
+                Ok(Ok(event)) => {
+                    pending.insert(event.paths.clone(), event);
+                }
+                Ok(Err(e)) => error!("Watch error: {e}"),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    if pending.is_empty() {
+                        continue;
+                    }
+                    let batch: Vec<Event> = pending.drain().map(|(_, event)| event).collect();
+                    self.apply_batch(batch).await;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    info!("Watcher channel disconnected, stopping");
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn apply_batch(&self, events: Vec<Event>) {
+        for event in events {
+            if let Err(e) = self.apply_event(event).await {
+                error!("Failed to apply fs event: {e}");
+            }
+        }
+    }
+
+    async fn apply_event(&self, event: Event) -> Result<(), KnawledgeError> {
+        match event.kind {
+            EventKind::Create(_) => {
+                for path in &event.paths {
+                    self.upsert_path(path).await?;
+                }
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                self.rename(&event.paths[0], &event.paths[1]).await?;
+            }
+            EventKind::Modify(_) => {
+                for path in &event.paths {
+                    self.upsert_path(path).await?;
+                }
+            }
+            EventKind::Remove(_) => {
+                for path in &event.paths {
+                    self.remove_path(path).await?;
+                }
+            }
+            _ => debug!("Ignoring event: {event:?}"),
+        }
+
+        Ok(())
+    }
+
+    /// Upserts a single path that was created or modified. Directories are
+    /// indexed recursively; markdown files are re-parsed and compared against
+    /// their stored hash so no-op modifies are dropped.
+    async fn upsert_path(&self, path: &Path) -> Result<(), KnawledgeError> {
+        if path.is_dir() {
+            let parent = path
+                .parent()
+                .and_then(|p| p.to_str())
+                .map(|p| self.db.get_dir_by_path(p));
+
+            let parent_id = match parent {
+                Some(fut) => fut.await?.map(|dir| dir.id),
+                None => None,
+            };
+
+            let job = crate::job::Job::new();
+            crate::document::process_directory(&self.db, path, parent_id, &job).await?;
+            return Ok(());
+        }
+
+        let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+            return Ok(());
+        };
+        if ext != "md" {
+            return Ok(());
+        }
+
+        let Some(parent_dir) = path.parent().and_then(|p| p.to_str()) else {
+            return Ok(());
+        };
+        let Some(directory) = self.db.get_dir_by_path(parent_dir).await? else {
+            warn!("{parent_dir}: directory not indexed yet, skipping {path:?}");
+            return Ok(());
+        };
+
+        let path_str = path.display().to_string();
+        let (document, meta) = Document::new(directory.id, Document::name(path), path_str)?;
+
+        match self.db.get_doc_by_path(&document.path).await? {
+            Some(existing) if existing.hash == document.hash => {
+                debug!("No content change for {}", document.path);
+                return Ok(());
+            }
+            Some(_) => self.db.update_doc(&document, &meta).await?,
+            None => self.db.insert_doc(&document, &meta).await?,
+        }
+
+        crate::document::recompute_directory_stats_chain(&self.db, directory.id).await?;
+
+        Ok(())
+    }
+
+    /// Removes a path that no longer exists on disk from the database.
+    async fn remove_path(&self, path: &Path) -> Result<(), KnawledgeError> {
+        let path_str = path.display().to_string();
+
+        if let Some(doc) = self.db.get_doc_by_path(&path_str).await? {
+            self.db
+                .delete_doc_by_name(doc.directory, &doc.file_name)
+                .await?;
+            crate::document::recompute_directory_stats_chain(&self.db, doc.directory).await?;
+            return Ok(());
+        }
+
+        if let Some(dir) = self.db.get_dir_by_path(&path_str).await? {
+            self.db.prune_directory_by_path(&path_str).await?;
+            if let Some(parent_id) = dir.parent {
+                crate::document::recompute_directory_stats_chain(&self.db, parent_id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves a record instead of deleting and re-inserting it, so custom IDs
+    /// and UUIDs survive a rename.
+    async fn rename(&self, from: &Path, to: &Path) -> Result<(), KnawledgeError> {
+        let from_str = from.display().to_string();
+        let to_str = to.display().to_string();
+
+        if let Some(dir) = self.db.get_dir_by_path(&from_str).await? {
+            self.db.rename_dir(dir.id, &to_str).await?;
+            crate::document::recompute_directory_stats_chain(&self.db, dir.id).await?;
+            return Ok(());
+        }
+
+        if let Some(doc) = self.db.get_doc_by_path(&from_str).await? {
+            let to_is_markdown = to.extension().and_then(|e| e.to_str()) == Some("md");
+
+            if to_is_markdown {
+                self.db
+                    .rename_doc(doc.directory, &doc.file_name, Document::name(to), &to_str)
+                    .await?;
+            } else {
+                // Renamed out from under us into something we no longer
+                // track (symmetric with the extension check in upsert_path);
+                // leaving the old row in place would point at a path that's
+                // no longer a markdown file.
+                self.db
+                    .delete_doc_by_name(doc.directory, &doc.file_name)
+                    .await?;
+            }
+
+            crate::document::recompute_directory_stats_chain(&self.db, doc.directory).await?;
+            return Ok(());
+        }
+
+        // Neither side was tracked (e.g. a rename into a watched root from
+        // outside it); fall back to treating the destination as new.
+        self.upsert_path(to).await
+    }
+}
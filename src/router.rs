@@ -0,0 +1,13 @@
+use axum::routing::{get, post};
+use axum::Router;
+
+use crate::job;
+use crate::state::State;
+
+/// Top-level route table for the service.
+pub fn router(state: State) -> Router {
+    Router::new()
+        .route("/jobs", get(job::list_jobs))
+        .route("/jobs/:id/cancel", post(job::cancel_job))
+        .with_state(state)
+}